@@ -0,0 +1,386 @@
+use std::ffi::{c_char, c_void};
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::Arc;
+
+use minijinja::value::Rest;
+use minijinja::{Error, ErrorKind, Value};
+
+use super::error::{self, MinijinjaError};
+use super::escape::{AutoEscapeRegistry, FormatterRegistry};
+use super::value::{cstr_to_str, json_to_value, string_to_cstring, take_c_string};
+
+/// Opaque handle wrapping a `minijinja::Environment`.
+///
+/// Always heap-allocated via `Box::into_raw` and released with
+/// [`minijinja_env_free`].
+pub struct MinijinjaEnv {
+    pub(crate) env: minijinja::Environment<'static>,
+    /// Lazily installed the first time a formatter is registered; see
+    /// `escape.rs`.
+    pub(crate) formatters: Option<Arc<FormatterRegistry>>,
+    /// Lazily installed the first time an auto-escape override is set; see
+    /// `escape.rs`.
+    pub(crate) auto_escape: Option<Arc<AutoEscapeRegistry>>,
+}
+
+#[no_mangle]
+pub extern "C" fn minijinja_env_new() -> *mut MinijinjaEnv {
+    Box::into_raw(Box::new(MinijinjaEnv {
+        env: minijinja::Environment::new(),
+        formatters: None,
+        auto_escape: None,
+    }))
+}
+
+/// # Safety
+/// `env` must be a pointer returned by [`minijinja_env_new`], or null.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_free(env: *mut MinijinjaEnv) {
+    if env.is_null() {
+        return;
+    }
+    drop(Box::from_raw(env));
+}
+
+/// # Safety
+/// `env`, `name` and `source` must be valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_add_template(
+    env: *mut MinijinjaEnv,
+    name: *const c_char,
+    source: *const c_char,
+    out_error: *mut *mut MinijinjaError,
+) -> c_int {
+    let name = cstr_to_str(name).to_owned();
+    let source = cstr_to_str(source).to_owned();
+    match (*env).env.add_template_owned(name, source) {
+        Ok(()) => 0,
+        Err(err) => {
+            error::set_out_error(out_error, &err);
+            -1
+        }
+    }
+}
+
+/// Callback used to marshal a call into a filter or function implemented in
+/// C#. `args_json` is a JSON array of the call arguments.
+///
+/// On success the callee writes a JSON value to `out_result` and returns `0`.
+/// On failure it writes a message to `out_error` and returns non-zero. Both
+/// out-params must be allocated with [`super::value::minijinja_alloc_string`]
+/// — this crate takes ownership of them and frees them with its own
+/// allocator via [`super::value::minijinja_string_free`], so a buffer
+/// allocated by the host's own runtime (e.g. .NET's marshaling heap) would
+/// be undefined behavior to free this way.
+///
+/// The callback may itself trigger a render of another template (e.g. to
+/// implement a C# filter in terms of minijinja) as each invocation borrows
+/// nothing across the call — it is safe to re-enter this crate's FFI
+/// surface from within the callback.
+pub type MinijinjaCallback = unsafe extern "C" fn(
+    args_json: *const c_char,
+    user_data: *mut c_void,
+    out_result: *mut *mut c_char,
+    out_error: *mut *mut c_char,
+) -> c_int;
+
+struct CallbackSlot {
+    func: MinijinjaCallback,
+    user_data: *mut c_void,
+}
+
+// SAFETY: the host guarantees `user_data` stays valid for as long as the
+// environment it was registered on is alive, and that invoking `func` is
+// safe from whatever thread renders a template.
+unsafe impl Send for CallbackSlot {}
+unsafe impl Sync for CallbackSlot {}
+
+fn invoke_callback(slot: &CallbackSlot, args: &[Value]) -> Result<Value, Error> {
+    let args_json = super::value::value_to_json(&Value::from(args.to_vec()));
+    let args_c = string_to_cstring(&args_json);
+    let mut out_result: *mut c_char = ptr::null_mut();
+    let mut out_error: *mut c_char = ptr::null_mut();
+    let rc = unsafe { (slot.func)(args_c.as_ptr(), slot.user_data, &mut out_result, &mut out_error) };
+    if rc != 0 {
+        let message = unsafe { take_c_string(out_error) }.unwrap_or_else(|| "callback failed".to_string());
+        return Err(Error::new(ErrorKind::InvalidOperation, message));
+    }
+    let result_json = unsafe { take_c_string(out_result) }.unwrap_or_default();
+    json_to_value(&result_json).map_err(|err| Error::new(ErrorKind::InvalidOperation, err.to_string()))
+}
+
+/// Register a filter whose body is a C# delegate.
+///
+/// # Safety
+/// `env` and `name` must be valid, and `callback`/`user_data` must remain
+/// valid for as long as `env` is alive.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_add_filter(
+    env: *mut MinijinjaEnv,
+    name: *const c_char,
+    callback: MinijinjaCallback,
+    user_data: *mut c_void,
+) {
+    let name = cstr_to_str(name).to_owned();
+    let slot = CallbackSlot { func: callback, user_data };
+    (*env).env.add_filter(name, move |value: Value, Rest(rest): Rest<Value>| -> Result<Value, Error> {
+        let mut args = vec![value];
+        args.extend(rest);
+        invoke_callback(&slot, &args)
+    });
+}
+
+/// Register a function whose body is a C# delegate.
+///
+/// # Safety
+/// Same contract as [`minijinja_env_add_filter`].
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_add_function(
+    env: *mut MinijinjaEnv,
+    name: *const c_char,
+    callback: MinijinjaCallback,
+    user_data: *mut c_void,
+) {
+    let name = cstr_to_str(name).to_owned();
+    let slot = CallbackSlot { func: callback, user_data };
+    (*env).env.add_function(name, move |Rest(args): Rest<Value>| -> Result<Value, Error> {
+        invoke_callback(&slot, &args)
+    });
+}
+
+/// Callback used to resolve a template by name on demand, for `{% extends %}`
+/// and `{% include %}` chains that aren't pre-registered via
+/// [`minijinja_env_add_template`].
+///
+/// The callee returns `0` and writes the source to `out_source` if the name
+/// was found, `1` (leaving `out_source` null) if it was not, or any other
+/// value with a message in `out_error` on failure. As with [`MinijinjaCallback`], both
+/// out-params must be allocated with
+/// [`super::value::minijinja_alloc_string`].
+pub type MinijinjaLoader = unsafe extern "C" fn(
+    name: *const c_char,
+    user_data: *mut c_void,
+    out_source: *mut *mut c_char,
+    out_error: *mut *mut c_char,
+) -> c_int;
+
+struct LoaderSlot {
+    func: MinijinjaLoader,
+    user_data: *mut c_void,
+}
+
+// SAFETY: same contract as `CallbackSlot` above.
+unsafe impl Send for LoaderSlot {}
+unsafe impl Sync for LoaderSlot {}
+
+/// Register a loader invoked lazily the first time a template name is
+/// referenced and not already loaded, letting C# back templates with the
+/// filesystem, embedded resources, or a database without pre-registering
+/// the whole tree.
+///
+/// # Safety
+/// `env` must be valid, and `callback`/`user_data` must remain valid for as
+/// long as `env` is alive.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_set_loader(
+    env: *mut MinijinjaEnv,
+    callback: MinijinjaLoader,
+    user_data: *mut c_void,
+) {
+    let slot = LoaderSlot { func: callback, user_data };
+    (*env).env.set_loader(move |name: &str| -> Result<Option<String>, Error> { invoke_loader(&slot, name) });
+}
+
+// Takes `slot` by reference (rather than accessing its fields directly in
+// the closure above) so the closure captures the whole `LoaderSlot` and
+// picks up its `unsafe impl Send + Sync` — with 2021-edition disjoint
+// closure capture, accessing `slot.func`/`slot.user_data` directly would
+// instead capture the bare `*mut c_void` field, which isn't `Send`/`Sync`.
+fn invoke_loader(slot: &LoaderSlot, name: &str) -> Result<Option<String>, Error> {
+    let name_c = string_to_cstring(name);
+    let mut out_source: *mut c_char = ptr::null_mut();
+    let mut out_error: *mut c_char = ptr::null_mut();
+    let rc = unsafe { (slot.func)(name_c.as_ptr(), slot.user_data, &mut out_source, &mut out_error) };
+    match rc {
+        0 => Ok(unsafe { take_c_string(out_source) }),
+        1 => Ok(None),
+        _ => {
+            let message = unsafe { take_c_string(out_error) }.unwrap_or_else(|| "loader failed".to_string());
+            Err(Error::new(ErrorKind::TemplateNotFound, message))
+        }
+    }
+}
+
+/// Callback used to forward a template's `log`/`debug` call to the host.
+/// `args_json` is a JSON array of the call's arguments and `level` is the
+/// optional severity the template author passed (e.g. `"warn"`), or null if
+/// omitted.
+pub type MinijinjaLogger = unsafe extern "C" fn(args_json: *const c_char, level: *const c_char, user_data: *mut c_void);
+
+struct LoggerSlot {
+    func: MinijinjaLogger,
+    user_data: *mut c_void,
+}
+
+// SAFETY: same contract as `CallbackSlot` above.
+unsafe impl Send for LoggerSlot {}
+unsafe impl Sync for LoggerSlot {}
+
+/// Register a `log`/`debug` global function that forwards its arguments and
+/// an optional severity level to a host-supplied logger instead of writing
+/// to the rendered output, giving template authors a side-channel for
+/// troubleshooting loops and conditionals.
+///
+/// # Safety
+/// `env` must be valid, and `callback`/`user_data` must remain valid for as
+/// long as `env` is alive.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_set_logger(
+    env: *mut MinijinjaEnv,
+    callback: MinijinjaLogger,
+    user_data: *mut c_void,
+) {
+    let slot = Arc::new(LoggerSlot { func: callback, user_data });
+
+    let log_slot = slot.clone();
+    (*env).env.add_function(
+        "log",
+        move |Rest(args): Rest<Value>, kwargs: minijinja::value::Kwargs| -> Value {
+            let level: Option<String> = kwargs.get("level").ok();
+            invoke_logger(&log_slot, &args, level.as_deref());
+            Value::from("")
+        },
+    );
+
+    (*env).env.add_function(
+        "debug",
+        move |Rest(args): Rest<Value>, kwargs: minijinja::value::Kwargs| -> Value {
+            let level: Option<String> = kwargs.get("level").ok();
+            invoke_logger(&slot, &args, level.as_deref());
+            Value::from("")
+        },
+    );
+}
+
+fn invoke_logger(slot: &LoggerSlot, args: &[Value], level: Option<&str>) {
+    let args_json = super::value::value_to_json(&Value::from(args.to_vec()));
+    let args_c = string_to_cstring(&args_json);
+    let level_c = level.map(string_to_cstring);
+    let level_ptr = level_c.as_ref().map(|c| c.as_ptr()).unwrap_or(ptr::null());
+    unsafe { (slot.func)(args_c.as_ptr(), level_ptr, slot.user_data) };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::ffi::template::{minijinja_env_get_template, minijinja_template_free, minijinja_template_render};
+    use crate::ffi::value::{copy_into_alloc, minijinja_string_free};
+
+    unsafe fn render(env: *mut MinijinjaEnv, name: &str, ctx_json: &str) -> String {
+        let name_c = string_to_cstring(name);
+        let mut err: *mut MinijinjaError = ptr::null_mut();
+        let tmpl = minijinja_env_get_template(env, name_c.as_ptr(), &mut err);
+        assert!(!tmpl.is_null(), "template lookup failed");
+        let ctx_c = string_to_cstring(ctx_json);
+        let mut render_err: *mut MinijinjaError = ptr::null_mut();
+        let out = minijinja_template_render(tmpl, ctx_c.as_ptr(), &mut render_err);
+        assert!(!out.is_null(), "render failed");
+        let rendered = CStr::from_ptr(out).to_str().unwrap().to_owned();
+        minijinja_string_free(out);
+        minijinja_template_free(tmpl);
+        rendered
+    }
+
+    // Increments the first (and only) argument by one; stands in for a C#
+    // delegate implementing a minijinja filter.
+    unsafe extern "C" fn inc_filter(
+        args_json: *const c_char,
+        _user_data: *mut c_void,
+        out_result: *mut *mut c_char,
+        _out_error: *mut *mut c_char,
+    ) -> c_int {
+        let args: Vec<i64> = serde_json::from_str(CStr::from_ptr(args_json).to_str().unwrap()).unwrap();
+        let result = serde_json::to_string(&(args[0] + 1)).unwrap();
+        *out_result = copy_into_alloc(result.as_bytes());
+        0
+    }
+
+    #[test]
+    fn add_filter_round_trips_through_json() {
+        unsafe {
+            let env = minijinja_env_new();
+            let name = string_to_cstring("inc");
+            minijinja_env_add_filter(env, name.as_ptr(), inc_filter, ptr::null_mut());
+
+            let tmpl_name = string_to_cstring("t");
+            let source = string_to_cstring("{{ value|inc }}");
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+            assert_eq!(minijinja_env_add_template(env, tmpl_name.as_ptr(), source.as_ptr(), &mut err), 0);
+
+            assert_eq!(render(env, "t", r#"{"value": 41}"#), "42");
+            minijinja_env_free(env);
+        }
+    }
+
+    // Resolves exactly one template name, standing in for a C# loader backed
+    // by the filesystem or embedded resources.
+    unsafe extern "C" fn known_name_loader(
+        name: *const c_char,
+        _user_data: *mut c_void,
+        out_source: *mut *mut c_char,
+        _out_error: *mut *mut c_char,
+    ) -> c_int {
+        if CStr::from_ptr(name).to_str().unwrap() == "lazy.txt" {
+            *out_source = copy_into_alloc(b"loaded: {{ value }}");
+            0
+        } else {
+            1
+        }
+    }
+
+    #[test]
+    fn set_loader_resolves_unregistered_templates_lazily() {
+        unsafe {
+            let env = minijinja_env_new();
+            minijinja_env_set_loader(env, known_name_loader, ptr::null_mut());
+            assert_eq!(render(env, "lazy.txt", r#"{"value": "ok"}"#), "loaded: ok");
+            minijinja_env_free(env);
+        }
+    }
+
+    static LOGGED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn capturing_logger(args_json: *const c_char, level: *const c_char, _user_data: *mut c_void) {
+        let args = CStr::from_ptr(args_json).to_str().unwrap().to_owned();
+        let level = if level.is_null() {
+            "<none>".to_string()
+        } else {
+            CStr::from_ptr(level).to_str().unwrap().to_owned()
+        };
+        LOGGED.lock().unwrap().push(format!("{level}: {args}"));
+    }
+
+    #[test]
+    fn set_logger_forwards_calls_and_emits_nothing() {
+        unsafe {
+            LOGGED.lock().unwrap().clear();
+            let env = minijinja_env_new();
+            minijinja_env_set_logger(env, capturing_logger, ptr::null_mut());
+
+            let name = string_to_cstring("t");
+            let source = string_to_cstring("before[{{ log(\"hi\", level=\"warn\") }}]after");
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+            minijinja_env_add_template(env, name.as_ptr(), source.as_ptr(), &mut err);
+
+            assert_eq!(render(env, "t", "{}"), "before[]after");
+            let logged = LOGGED.lock().unwrap();
+            assert_eq!(logged.len(), 1);
+            assert!(logged[0].starts_with("warn: "));
+            minijinja_env_free(env);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::ffi::{c_char, CString};
+use std::os::raw::c_int;
+
+use minijinja::Error;
+
+use super::value::string_to_cstring;
+
+/// Owned, heap-allocated error handle returned across the FFI boundary in
+/// place of a flat message, so C# can surface precise diagnostics (the
+/// template name, line, and byte range) rather than a stringified blob.
+///
+/// Every field is rendered into an owned `CString` (or plain integer) at
+/// construction time, so the getters below are infallible pointer reads
+/// with no borrow back into the originating `minijinja::Error`.
+pub struct MinijinjaError {
+    kind: CString,
+    detail: CString,
+    template_name: Option<CString>,
+    line: i32,
+    range_start: i32,
+    range_end: i32,
+}
+
+impl MinijinjaError {
+    fn from_minijinja(err: &Error) -> Self {
+        let range = err.range();
+        MinijinjaError {
+            kind: string_to_cstring(&format!("{:?}", err.kind())),
+            detail: string_to_cstring(&err.to_string()),
+            template_name: err.name().map(string_to_cstring),
+            line: err.line().map(|line| line as i32).unwrap_or(-1),
+            range_start: range.as_ref().map(|r| r.start as i32).unwrap_or(-1),
+            range_end: range.as_ref().map(|r| r.end as i32).unwrap_or(-1),
+        }
+    }
+}
+
+/// Allocate a [`MinijinjaError`] from a `minijinja::Error` and write it into
+/// a caller-provided out-param, if any.
+pub(crate) fn set_out_error(out_error: *mut *mut MinijinjaError, err: &Error) {
+    if out_error.is_null() {
+        return;
+    }
+    unsafe {
+        *out_error = Box::into_raw(Box::new(MinijinjaError::from_minijinja(err)));
+    }
+}
+
+/// # Safety
+/// `err` must be a pointer handed back by this crate, or null.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_error_free(err: *mut MinijinjaError) {
+    if err.is_null() {
+        return;
+    }
+    drop(Box::from_raw(err));
+}
+
+/// # Safety
+/// `err` must be a live pointer handed back by this crate.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_error_kind(err: *const MinijinjaError) -> *const c_char {
+    (*err).kind.as_ptr()
+}
+
+/// # Safety
+/// `err` must be a live pointer handed back by this crate.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_error_detail(err: *const MinijinjaError) -> *const c_char {
+    (*err).detail.as_ptr()
+}
+
+/// Returns null if the error isn't associated with a named template.
+///
+/// # Safety
+/// `err` must be a live pointer handed back by this crate.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_error_template_name(err: *const MinijinjaError) -> *const c_char {
+    (*err)
+        .template_name
+        .as_ref()
+        .map(|name| name.as_ptr())
+        .unwrap_or(std::ptr::null())
+}
+
+/// Returns `-1` if the error has no associated line.
+///
+/// # Safety
+/// `err` must be a live pointer handed back by this crate.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_error_line(err: *const MinijinjaError) -> c_int {
+    (*err).line
+}
+
+/// Writes the error's byte range into `out_start`/`out_end`, or `-1` into
+/// both if the error has no associated range.
+///
+/// # Safety
+/// `err` must be a live pointer handed back by this crate, and `out_start`/
+/// `out_end` must either be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_error_range(err: *const MinijinjaError, out_start: *mut c_int, out_end: *mut c_int) {
+    if !out_start.is_null() {
+        *out_start = (*err).range_start;
+    }
+    if !out_end.is_null() {
+        *out_end = (*err).range_end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_void, CStr};
+    use std::ptr;
+
+    use super::*;
+    use crate::ffi::env::{minijinja_env_add_template, minijinja_env_free, minijinja_env_new, minijinja_env_set_loader};
+    use crate::ffi::template::minijinja_env_get_template;
+    use crate::ffi::value::{copy_into_alloc, string_to_cstring};
+
+    #[test]
+    fn syntax_error_populates_kind_detail_and_name() {
+        unsafe {
+            let env = minijinja_env_new();
+            let name = string_to_cstring("broken.txt");
+            let source = string_to_cstring("{{ unterminated");
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+
+            let rc = minijinja_env_add_template(env, name.as_ptr(), source.as_ptr(), &mut err);
+            assert_ne!(rc, 0);
+            assert!(!err.is_null());
+
+            let kind = CStr::from_ptr(minijinja_error_kind(err)).to_str().unwrap();
+            assert!(!kind.is_empty());
+            let detail = CStr::from_ptr(minijinja_error_detail(err)).to_str().unwrap();
+            assert!(!detail.is_empty());
+            assert_eq!(CStr::from_ptr(minijinja_error_template_name(err)).to_str().unwrap(), "broken.txt");
+
+            minijinja_error_free(err);
+            minijinja_env_free(env);
+        }
+    }
+
+    // Stands in for a C# loader delegate that always fails; the resulting
+    // error is built via `Error::new(..)` in `invoke_loader` before any
+    // template name is recorded on the environment, so it carries none.
+    unsafe extern "C" fn failing_loader(
+        _name: *const c_char,
+        _user_data: *mut c_void,
+        _out_source: *mut *mut c_char,
+        out_error: *mut *mut c_char,
+    ) -> c_int {
+        *out_error = copy_into_alloc(b"boom");
+        -1
+    }
+
+    #[test]
+    fn error_without_a_template_name_reports_null() {
+        unsafe {
+            let env = minijinja_env_new();
+            minijinja_env_set_loader(env, failing_loader, ptr::null_mut());
+
+            let name = string_to_cstring("missing.txt");
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+            let tmpl = minijinja_env_get_template(env, name.as_ptr(), &mut err);
+            assert!(tmpl.is_null());
+            assert!(!err.is_null());
+            assert!(minijinja_error_template_name(err).is_null());
+
+            minijinja_error_free(err);
+            minijinja_env_free(env);
+        }
+    }
+}
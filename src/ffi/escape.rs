@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void};
+use std::os::raw::c_int;
+use std::sync::Mutex;
+
+use minijinja::value::ValueKind;
+use minijinja::{default_auto_escape_callback, AutoEscape, Error, ErrorKind, Output, State, Value};
+
+use super::env::MinijinjaEnv;
+use super::value::{cstr_to_str, string_to_cstring, take_c_string, value_to_json};
+
+/// Auto-escape mode a template should render under, mirrored from
+/// `minijinja::AutoEscape` for the FFI boundary.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum MinijinjaAutoEscapeMode {
+    None = 0,
+    Html = 1,
+    Json = 2,
+}
+
+impl From<MinijinjaAutoEscapeMode> for AutoEscape {
+    fn from(mode: MinijinjaAutoEscapeMode) -> Self {
+        match mode {
+            MinijinjaAutoEscapeMode::None => AutoEscape::None,
+            MinijinjaAutoEscapeMode::Html => AutoEscape::Html,
+            MinijinjaAutoEscapeMode::Json => AutoEscape::Json,
+        }
+    }
+}
+
+/// Per-template-name auto-escape overrides, consulted by the callback
+/// installed on first use (mirrors [`super::escape::FormatterRegistry`]'s
+/// lazy-install pattern).
+#[derive(Default)]
+pub(crate) struct AutoEscapeRegistry {
+    modes: Mutex<HashMap<String, AutoEscape>>,
+}
+
+/// Set the auto-escape mode a specific template renders under. Names that
+/// never had a mode set fall back to minijinja's extension-based default.
+///
+/// # Safety
+/// `env` and `name` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_set_auto_escape(
+    env: *mut MinijinjaEnv,
+    name: *const c_char,
+    mode: MinijinjaAutoEscapeMode,
+) {
+    let name = cstr_to_str(name).to_owned();
+    let escape: AutoEscape = mode.into();
+    let handle = &mut *env;
+    if handle.auto_escape.is_none() {
+        handle.auto_escape = Some(std::sync::Arc::new(AutoEscapeRegistry::default()));
+        let registry = handle.auto_escape.clone().unwrap();
+        handle.env.set_auto_escape_callback(move |name: &str| {
+            registry
+                .modes
+                .lock()
+                .unwrap()
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| default_auto_escape_callback(name))
+        });
+    }
+    let registry = handle.auto_escape.clone().unwrap();
+    registry.modes.lock().unwrap().insert(name, escape);
+}
+
+/// Callback used to format a value for output. Receives the value as JSON
+/// and returns a string to be written verbatim (i.e. already escaped as
+/// appropriate) in place of the value. As with
+/// [`super::env::MinijinjaCallback`], `out_result`/`out_error` must be
+/// allocated with [`super::value::minijinja_alloc_string`] — this crate
+/// frees them with its own allocator.
+pub type MinijinjaFormatter = unsafe extern "C" fn(
+    value_json: *const c_char,
+    user_data: *mut c_void,
+    out_result: *mut *mut c_char,
+    out_error: *mut *mut c_char,
+) -> c_int;
+
+#[derive(Clone, Copy)]
+struct FormatterSlot {
+    func: MinijinjaFormatter,
+    user_data: *mut c_void,
+}
+
+// SAFETY: same contract as `CallbackSlot` in `env.rs`.
+unsafe impl Send for FormatterSlot {}
+unsafe impl Sync for FormatterSlot {}
+
+/// Registered formatters keyed by name, consulted against a value's
+/// `format` attribute (see [`minijinja_env_add_formatter`]).
+#[derive(Default)]
+pub(crate) struct FormatterRegistry {
+    formatters: Mutex<HashMap<String, FormatterSlot>>,
+}
+
+/// Install a named formatter, used whenever a rendered value carries a
+/// matching `format` attribute (e.g. a map with a `format` key set to that
+/// name). Installing the first formatter on an environment also installs
+/// minijinja's output formatter hook; later calls just add to the registry.
+///
+/// # Safety
+/// `env` and `name` must be valid, and `callback`/`user_data` must remain
+/// valid for as long as `env` is alive.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_add_formatter(
+    env: *mut MinijinjaEnv,
+    name: *const c_char,
+    callback: MinijinjaFormatter,
+    user_data: *mut c_void,
+) {
+    let name = cstr_to_str(name).to_owned();
+    let handle = &mut *env;
+    if handle.formatters.is_none() {
+        handle.formatters = Some(std::sync::Arc::new(FormatterRegistry::default()));
+        let registry = handle.formatters.clone().unwrap();
+        handle.env.set_formatter(move |out: &mut Output, state: &State, value: &Value| {
+            format_value(&registry, out, state, value)
+        });
+    }
+    let registry = handle.formatters.clone().unwrap();
+    registry
+        .formatters
+        .lock()
+        .unwrap()
+        .insert(name, FormatterSlot { func: callback, user_data });
+}
+
+fn format_value(registry: &FormatterRegistry, out: &mut Output, state: &State, value: &Value) -> Result<(), Error> {
+    let format_name = if value.kind() == ValueKind::Map {
+        value.get_attr("format").ok().filter(|v| !v.is_undefined()).map(|v| v.to_string())
+    } else {
+        None
+    };
+
+    let Some(format_name) = format_name else {
+        return minijinja::escape_formatter(out, state, value);
+    };
+
+    // Copy the slot out and drop the guard *before* invoking the callback:
+    // the callback may re-enter rendering on this environment (e.g. render
+    // a sub-template containing another formatted value), and `Mutex` is
+    // not reentrant.
+    let slot = {
+        let formatters = registry.formatters.lock().unwrap();
+        match formatters.get(&format_name) {
+            Some(slot) => *slot,
+            None => return minijinja::escape_formatter(out, state, value),
+        }
+    };
+
+    let value_json = value_to_json(value);
+    let value_c = string_to_cstring(&value_json);
+    let mut out_result: *mut c_char = std::ptr::null_mut();
+    let mut out_error: *mut c_char = std::ptr::null_mut();
+    let rc = unsafe { (slot.func)(value_c.as_ptr(), slot.user_data, &mut out_result, &mut out_error) };
+    if rc != 0 {
+        let message = unsafe { take_c_string(out_error) }.unwrap_or_else(|| "formatter failed".to_string());
+        return Err(Error::new(ErrorKind::InvalidOperation, message));
+    }
+    let rendered = unsafe { take_c_string(out_result) }.unwrap_or_default();
+    out.write_str(&rendered)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::ptr;
+
+    use super::*;
+    use crate::ffi::env::{minijinja_env_add_template, minijinja_env_free, minijinja_env_new, MinijinjaEnv};
+    use crate::ffi::error::MinijinjaError;
+    use crate::ffi::template::{minijinja_env_get_template, minijinja_template_free, minijinja_template_render};
+    use crate::ffi::value::{copy_into_alloc, minijinja_string_free};
+
+    unsafe fn render(env: *mut MinijinjaEnv, name: &str, ctx_json: &str) -> String {
+        let name_c = string_to_cstring(name);
+        let mut err: *mut MinijinjaError = ptr::null_mut();
+        let tmpl = minijinja_env_get_template(env, name_c.as_ptr(), &mut err);
+        assert!(!tmpl.is_null(), "template lookup failed");
+        let ctx_c = string_to_cstring(ctx_json);
+        let mut render_err: *mut MinijinjaError = ptr::null_mut();
+        let out = minijinja_template_render(tmpl, ctx_c.as_ptr(), &mut render_err);
+        assert!(!out.is_null(), "render failed");
+        let rendered = CStr::from_ptr(out).to_str().unwrap().to_owned();
+        minijinja_string_free(out);
+        minijinja_template_free(tmpl);
+        rendered
+    }
+
+    unsafe extern "C" fn shout_formatter(
+        value_json: *const c_char,
+        _user_data: *mut c_void,
+        out_result: *mut *mut c_char,
+        _out_error: *mut *mut c_char,
+    ) -> c_int {
+        let value: serde_json::Value = serde_json::from_str(CStr::from_ptr(value_json).to_str().unwrap()).unwrap();
+        let text = value.get("text").and_then(|v| v.as_str()).unwrap_or_default();
+        *out_result = copy_into_alloc(text.to_uppercase().as_bytes());
+        0
+    }
+
+    #[test]
+    fn formatter_dispatches_by_format_attribute() {
+        unsafe {
+            let env = minijinja_env_new();
+            let name = string_to_cstring("shout");
+            minijinja_env_add_formatter(env, name.as_ptr(), shout_formatter, ptr::null_mut());
+
+            let tmpl_name = string_to_cstring("t");
+            let source = string_to_cstring("{{ value }}");
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+            minijinja_env_add_template(env, tmpl_name.as_ptr(), source.as_ptr(), &mut err);
+
+            let rendered = render(env, "t", r#"{"value": {"format": "shout", "text": "hi"}}"#);
+            assert_eq!(rendered, "HI");
+            minijinja_env_free(env);
+        }
+    }
+
+    // Re-enters `minijinja_template_render` on the same environment from
+    // inside the formatter callback, reproducing the scenario that deadlocked
+    // before the formatter lock was dropped prior to the callback.
+    unsafe extern "C" fn reentrant_formatter(
+        value_json: *const c_char,
+        user_data: *mut c_void,
+        out_result: *mut *mut c_char,
+        _out_error: *mut *mut c_char,
+    ) -> c_int {
+        let env = user_data as *mut MinijinjaEnv;
+        let rendered = render(env, "inner", "{}");
+        let value: serde_json::Value = serde_json::from_str(CStr::from_ptr(value_json).to_str().unwrap()).unwrap();
+        let text = value.get("text").and_then(|v| v.as_str()).unwrap_or_default();
+        *out_result = copy_into_alloc(format!("{text}+{rendered}").as_bytes());
+        0
+    }
+
+    #[test]
+    fn formatter_can_reenter_render_without_deadlocking() {
+        unsafe {
+            let env = minijinja_env_new();
+            let name = string_to_cstring("reentrant");
+            minijinja_env_add_formatter(env, name.as_ptr(), reentrant_formatter, env as *mut c_void);
+
+            let inner_name = string_to_cstring("inner");
+            let inner_source = string_to_cstring("inner-ok");
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+            minijinja_env_add_template(env, inner_name.as_ptr(), inner_source.as_ptr(), &mut err);
+
+            let outer_name = string_to_cstring("t");
+            let outer_source = string_to_cstring("{{ value }}");
+            minijinja_env_add_template(env, outer_name.as_ptr(), outer_source.as_ptr(), &mut err);
+
+            let rendered = render(env, "t", r#"{"value": {"format": "reentrant", "text": "outer"}}"#);
+            assert_eq!(rendered, "outer+inner-ok");
+            minijinja_env_free(env);
+        }
+    }
+
+    #[test]
+    fn auto_escape_mode_is_per_template_name() {
+        unsafe {
+            let env = minijinja_env_new();
+            let html_name = string_to_cstring("html.txt");
+            let plain_name = string_to_cstring("plain.txt");
+            minijinja_env_set_auto_escape(env, html_name.as_ptr(), MinijinjaAutoEscapeMode::Html);
+            minijinja_env_set_auto_escape(env, plain_name.as_ptr(), MinijinjaAutoEscapeMode::None);
+
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+            let source = string_to_cstring("{{ value }}");
+            minijinja_env_add_template(env, html_name.as_ptr(), source.as_ptr(), &mut err);
+            minijinja_env_add_template(env, plain_name.as_ptr(), source.as_ptr(), &mut err);
+
+            assert_eq!(render(env, "html.txt", r#"{"value": "<b>hi</b>"}"#), "&lt;b&gt;hi&lt;&#x2f;b&gt;");
+            assert_eq!(render(env, "plain.txt", r#"{"value": "<b>hi</b>"}"#), "<b>hi</b>");
+            minijinja_env_free(env);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod env;
+pub mod error;
+pub mod escape;
+pub mod template;
+pub mod value;
@@ -0,0 +1,231 @@
+use std::ffi::{c_char, c_void};
+use std::io;
+use std::os::raw::c_int;
+use std::ptr;
+
+use super::env::MinijinjaEnv;
+use super::error::{self, MinijinjaError};
+use super::value::{cstr_to_str, json_error_to_minijinja, json_to_value, string_to_cstring};
+
+/// Opaque handle to a template resolved from an [`MinijinjaEnv`].
+///
+/// Holds the owning environment pointer and the template name rather than
+/// borrowing `minijinja::Template` directly, since the latter's lifetime is
+/// tied to the environment in a way that doesn't survive the FFI boundary.
+pub struct MinijinjaTemplate {
+    env: *mut MinijinjaEnv,
+    name: String,
+}
+
+/// Resolve a template by name.
+///
+/// # Safety
+/// `env` and `name` must be valid, and `env` must outlive the returned
+/// handle.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_env_get_template(
+    env: *mut MinijinjaEnv,
+    name: *const c_char,
+    out_error: *mut *mut MinijinjaError,
+) -> *mut MinijinjaTemplate {
+    let name = cstr_to_str(name);
+    match (*env).env.get_template(name) {
+        Ok(_) => Box::into_raw(Box::new(MinijinjaTemplate {
+            env,
+            name: name.to_owned(),
+        })),
+        Err(err) => {
+            error::set_out_error(out_error, &err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `tmpl` must be a pointer returned by [`minijinja_env_get_template`], or
+/// null.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_template_free(tmpl: *mut MinijinjaTemplate) {
+    if tmpl.is_null() {
+        return;
+    }
+    drop(Box::from_raw(tmpl));
+}
+
+/// Render a template against a JSON-encoded context, returning an owned,
+/// NUL-terminated string to be freed with
+/// [`super::value::minijinja_string_free`].
+///
+/// # Safety
+/// `tmpl` and `context_json` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_template_render(
+    tmpl: *mut MinijinjaTemplate,
+    context_json: *const c_char,
+    out_error: *mut *mut MinijinjaError,
+) -> *mut c_char {
+    let handle = &*tmpl;
+    let template = match (*handle.env).env.get_template(&handle.name) {
+        Ok(template) => template,
+        Err(err) => {
+            error::set_out_error(out_error, &err);
+            return ptr::null_mut();
+        }
+    };
+    let ctx = match json_to_value(cstr_to_str(context_json)) {
+        Ok(ctx) => ctx,
+        Err(err) => {
+            error::set_out_error(out_error, &json_error_to_minijinja(err));
+            return ptr::null_mut();
+        }
+    };
+    match template.render(ctx) {
+        Ok(rendered) => string_to_cstring(&rendered).into_raw(),
+        Err(err) => {
+            error::set_out_error(out_error, &err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Callback that receives a chunk of rendered output as it's produced.
+/// Returns `0` to keep rendering, or non-zero to abort the render.
+pub type MinijinjaWriteFn = unsafe extern "C" fn(chunk: *const u8, len: usize, user_data: *mut c_void) -> c_int;
+
+struct CallbackWriter {
+    func: MinijinjaWriteFn,
+    user_data: *mut c_void,
+}
+
+impl io::Write for CallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let rc = unsafe { (self.func)(buf.as_ptr(), buf.len(), self.user_data) };
+        if rc != 0 {
+            return Err(io::Error::other("write callback aborted the render"));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Render a template against a JSON-encoded context, flushing chunks to
+/// `write_fn` as they're produced instead of materializing the whole
+/// document in memory — useful for large reports or server responses that
+/// can be piped directly into a network stream or file.
+///
+/// Returns `0` on success, non-zero on failure (with `out_error` set).
+///
+/// # Safety
+/// `tmpl` and `context_json` must be valid, and `write_fn`/`user_data` must
+/// remain valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_template_render_to_callback(
+    tmpl: *mut MinijinjaTemplate,
+    context_json: *const c_char,
+    write_fn: MinijinjaWriteFn,
+    user_data: *mut c_void,
+    out_error: *mut *mut MinijinjaError,
+) -> c_int {
+    let handle = &*tmpl;
+    let template = match (*handle.env).env.get_template(&handle.name) {
+        Ok(template) => template,
+        Err(err) => {
+            error::set_out_error(out_error, &err);
+            return -1;
+        }
+    };
+    let ctx = match json_to_value(cstr_to_str(context_json)) {
+        Ok(ctx) => ctx,
+        Err(err) => {
+            error::set_out_error(out_error, &json_error_to_minijinja(err));
+            return -1;
+        }
+    };
+    let writer = CallbackWriter { func: write_fn, user_data };
+    match template.render_to_write(ctx, writer) {
+        Ok(_state) => 0,
+        Err(err) => {
+            error::set_out_error(out_error, &err);
+            -1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use crate::ffi::env::{minijinja_env_add_template, minijinja_env_free, minijinja_env_new};
+    use crate::ffi::error::minijinja_error_free;
+    use crate::ffi::value::{minijinja_string_free, string_to_cstring};
+
+    unsafe extern "C" fn collect_into_vec(chunk: *const u8, len: usize, user_data: *mut c_void) -> c_int {
+        let buf = &mut *(user_data as *mut Vec<u8>);
+        buf.extend_from_slice(std::slice::from_raw_parts(chunk, len));
+        0
+    }
+
+    #[test]
+    fn render_to_callback_matches_buffered_render() {
+        unsafe {
+            let env = minijinja_env_new();
+            let name = string_to_cstring("t");
+            let source = string_to_cstring("hello {{ name }}, item count: {{ items|length }}");
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+            minijinja_env_add_template(env, name.as_ptr(), source.as_ptr(), &mut err);
+
+            let ctx = string_to_cstring(r#"{"name": "Ririko", "items": [1, 2, 3]}"#);
+
+            let tmpl = minijinja_env_get_template(env, name.as_ptr(), &mut err);
+            let mut chunks: Vec<u8> = Vec::new();
+            let rc = minijinja_template_render_to_callback(
+                tmpl,
+                ctx.as_ptr(),
+                collect_into_vec,
+                &mut chunks as *mut Vec<u8> as *mut c_void,
+                &mut err,
+            );
+            assert_eq!(rc, 0);
+            let streamed = String::from_utf8(chunks).unwrap();
+
+            let buffered_ptr = minijinja_template_render(tmpl, ctx.as_ptr(), &mut err);
+            let buffered = std::ffi::CStr::from_ptr(buffered_ptr).to_str().unwrap().to_owned();
+            minijinja_string_free(buffered_ptr);
+
+            assert_eq!(streamed, buffered);
+            assert_eq!(streamed, "hello Ririko, item count: 3");
+
+            minijinja_template_free(tmpl);
+            minijinja_env_free(env);
+        }
+    }
+
+    #[test]
+    fn render_to_callback_propagates_abort_as_error() {
+        unsafe extern "C" fn abort_immediately(_chunk: *const u8, _len: usize, _user_data: *mut c_void) -> c_int {
+            1
+        }
+
+        unsafe {
+            let env = minijinja_env_new();
+            let name = string_to_cstring("t");
+            let source = string_to_cstring("hello {{ name }}");
+            let mut err: *mut MinijinjaError = ptr::null_mut();
+            minijinja_env_add_template(env, name.as_ptr(), source.as_ptr(), &mut err);
+
+            let ctx = string_to_cstring(r#"{"name": "Ririko"}"#);
+            let tmpl = minijinja_env_get_template(env, name.as_ptr(), &mut err);
+            let rc = minijinja_template_render_to_callback(tmpl, ctx.as_ptr(), abort_immediately, ptr::null_mut(), &mut err);
+            assert_ne!(rc, 0);
+            assert!(!err.is_null());
+
+            minijinja_error_free(err);
+            minijinja_template_free(tmpl);
+            minijinja_env_free(env);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::ffi::{c_char, CStr, CString};
+
+use minijinja::Value;
+
+/// Borrow a `&str` out of a NUL-terminated C string.
+///
+/// Invalid UTF-8 is treated as an empty string rather than panicking across
+/// the FFI boundary.
+///
+/// # Safety
+/// `ptr` must be a valid, NUL-terminated C string for the lifetime `'a`.
+pub unsafe fn cstr_to_str<'a>(ptr: *const c_char) -> &'a str {
+    CStr::from_ptr(ptr).to_str().unwrap_or_default()
+}
+
+/// Allocate an owned `CString`, collapsing interior NULs to an empty string
+/// rather than panicking.
+pub fn string_to_cstring(s: &str) -> CString {
+    CString::new(s).unwrap_or_default()
+}
+
+/// Take ownership of a C string previously allocated by this crate, freeing
+/// it in the process. Returns `None` for a null pointer.
+///
+/// # Safety
+/// `ptr` must be null, or a pointer obtained from one of this crate's
+/// exported functions and not already freed.
+pub unsafe fn take_c_string(ptr: *mut c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let owned = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    minijinja_string_free(ptr);
+    Some(owned)
+}
+
+/// Serialize a `Value` to JSON for crossing the FFI boundary.
+pub fn value_to_json(value: &Value) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "null".to_string())
+}
+
+/// Deserialize a `Value` out of JSON received from the host.
+pub fn json_to_value(json: &str) -> serde_json::Result<Value> {
+    serde_json::from_str(json)
+}
+
+/// Wrap a JSON decoding failure as a minijinja error, so it can be reported
+/// through the same structured error path as template errors.
+pub fn json_error_to_minijinja(err: serde_json::Error) -> minijinja::Error {
+    minijinja::Error::new(minijinja::ErrorKind::BadSerialization, err.to_string())
+}
+
+/// Free a string previously returned to the host by this crate.
+///
+/// # Safety
+/// `s` must be a pointer obtained from one of this crate's exported
+/// functions, or null.
+#[no_mangle]
+pub unsafe extern "C" fn minijinja_string_free(s: *mut c_char) {
+    if s.is_null() {
+        return;
+    }
+    drop(CString::from_raw(s));
+}
+
+/// Allocate a Rust-owned, writable buffer of exactly `len` bytes, NUL-padded
+/// just past the end.
+///
+/// Host callbacks (filters, functions, loaders, formatters) must allocate any
+/// buffer they hand back through an `out_result`/`out_source`/`out_error`
+/// out-param with this function rather than with their own runtime's heap:
+/// those out-params are later taken ownership of and freed through this
+/// crate's allocator (see [`take_c_string`]), and freeing a buffer this
+/// crate didn't allocate is undefined behavior. Write exactly `len` bytes
+/// into the returned buffer; the trailing NUL is already in place.
+#[no_mangle]
+pub extern "C" fn minijinja_alloc_string(len: usize) -> *mut c_char {
+    unsafe { CString::from_vec_unchecked(vec![0u8; len]) }.into_raw()
+}
+
+/// Copy `bytes` into a freshly [`minijinja_alloc_string`]-allocated buffer,
+/// the way a real host binding would before handing a string back through an
+/// out-param. Used by this crate's own tests to stand in for a C# callback
+/// without reaching into private allocation helpers.
+#[cfg(test)]
+pub(crate) unsafe fn copy_into_alloc(bytes: &[u8]) -> *mut c_char {
+    let buf = minijinja_alloc_string(bytes.len());
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf as *mut u8, bytes.len());
+    buf
+}
@@ -0,0 +1,8 @@
+//! FFI surface exposed to the C# bindings.
+//!
+//! This crate is built as a `cdylib` and consumed from C# via `DllImport`.
+//! Every exported function takes or returns raw pointers; ownership is
+//! documented on each function, and anything handed back to the host must
+//! be released with the matching `minijinja_*_free` call.
+
+pub mod ffi;